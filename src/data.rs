@@ -1,5 +1,12 @@
 use actix_web::{
-	http::header::{HeaderValue, TryIntoHeaderPair, TryIntoHeaderValue, STRICT_TRANSPORT_SECURITY},
+	http::{
+		header::{
+			HeaderName, HeaderValue, TryIntoHeaderPair, TryIntoHeaderValue,
+			CONTENT_SECURITY_POLICY, REFERRER_POLICY, STRICT_TRANSPORT_SECURITY,
+			X_CONTENT_TYPE_OPTIONS, X_FRAME_OPTIONS,
+		},
+		StatusCode,
+	},
 	HttpResponse,
 };
 use serde::{Deserialize, Deserializer};
@@ -12,6 +19,206 @@ pub struct Redirect {
 	pub port: Option<u16>,
 	/// redirect to tls port for ipv4,ipv6 or both protocols
 	pub protocols: Protocols,
+	/// http status code used for the redirection (308)
+	#[serde(default)]
+	pub status: RedirectStatus,
+	/// path prefixes served over plain http (ACME challenge by default)
+	#[serde(default = "default_exclude_paths")]
+	pub exclude_paths: Vec<String>,
+	/// host rules: `host`/glob entries allow, `!host` entries deny. A matching
+	/// denial always wins; with only allowances, non-matching hosts are left on
+	/// http; an empty list redirects every host
+	#[serde(default)]
+	pub hosts: Vec<HostDescription>,
+	/// redirect WebSocket upgrade handshakes instead of forwarding them (false)
+	#[serde(default)]
+	pub redirect_upgrades: bool,
+	/// response hardening headers (HSTS and friends)
+	#[serde(default)]
+	pub security_headers: SecurityHeaders,
+}
+
+pub(crate) fn default_exclude_paths() -> Vec<String> {
+	vec!["/.well-known/acme-challenge/".to_string()]
+}
+
+/// A host matcher, either an exact hostname or a glob pattern (`*`, `?`, `[]`).
+#[derive(Clone)]
+enum HostMatcher {
+	Exact(String),
+	Pattern(String),
+}
+
+// Match `text` against a shell-style glob supporting `*`, `?` and `[]` classes.
+fn glob_match(pattern: &str, text: &str) -> bool {
+	let p: Vec<char> = pattern.chars().collect();
+	let t: Vec<char> = text.chars().collect();
+	let (mut pi, mut ti) = (0, 0);
+	// last `*` position so we can backtrack greedily
+	let (mut star_p, mut star_t): (Option<usize>, usize) = (None, 0);
+	while ti < t.len() {
+		let advanced = pi < p.len()
+			&& match p[pi] {
+				'*' => {
+					star_p = Some(pi);
+					star_t = ti;
+					pi += 1;
+					continue;
+				}
+				'?' => {
+					pi += 1;
+					ti += 1;
+					true
+				}
+				'[' => match match_class(&p, pi, t[ti]) {
+					Some((matched, next)) if matched => {
+						pi = next;
+						ti += 1;
+						true
+					}
+					Some(_) => false,
+					// unterminated class: treat `[` as a literal
+					None => {
+						if t[ti] == '[' {
+							pi += 1;
+							ti += 1;
+							true
+						} else {
+							false
+						}
+					}
+				},
+				c => {
+					if c == t[ti] {
+						pi += 1;
+						ti += 1;
+						true
+					} else {
+						false
+					}
+				}
+			};
+		if advanced {
+			continue;
+		}
+		// mismatch: backtrack to the last `*`, consuming one more char
+		match star_p {
+			Some(sp) => {
+				pi = sp + 1;
+				star_t += 1;
+				ti = star_t;
+			}
+			None => return false,
+		}
+	}
+	// trailing `*` in the pattern match the empty string
+	while pi < p.len() && p[pi] == '*' {
+		pi += 1;
+	}
+	pi == p.len()
+}
+
+// Match a single char against a `[...]` class starting at `start`, returning
+// whether it matched and the index just past the closing `]`. `None` if the
+// class is unterminated.
+fn match_class(p: &[char], start: usize, c: char) -> Option<(bool, usize)> {
+	let mut i = start + 1;
+	let negate = matches!(p.get(i), Some('!') | Some('^'));
+	if negate {
+		i += 1;
+	}
+	let mut matched = false;
+	let mut first = true;
+	while i < p.len() {
+		if p[i] == ']' && !first {
+			return Some((matched ^ negate, i + 1));
+		}
+		first = false;
+		if i + 2 < p.len() && p[i + 1] == '-' && p[i + 2] != ']' {
+			if p[i] <= c && c <= p[i + 2] {
+				matched = true;
+			}
+			i += 3;
+		} else {
+			if p[i] == c {
+				matched = true;
+			}
+			i += 1;
+		}
+	}
+	None
+}
+
+/// A host rule: a matcher plus its polarity. A rule prefixed with `!` in config
+/// is a denial (redirect every host *except* matches), otherwise an allowance.
+#[derive(Clone)]
+pub struct HostDescription {
+	matcher: HostMatcher,
+	negated: bool,
+}
+
+impl HostDescription {
+	/// Returns true when `hostname` matches this rule's pattern, ignoring polarity.
+	pub fn matches(&self, hostname: &str) -> bool {
+		match &self.matcher {
+			HostMatcher::Exact(host) => host == hostname,
+			HostMatcher::Pattern(pattern) => glob_match(pattern, hostname),
+		}
+	}
+
+	/// Whether this rule denies (`!` prefix) rather than allows.
+	pub fn is_denial(&self) -> bool {
+		self.negated
+	}
+}
+
+impl<'de> Deserialize<'de> for HostDescription {
+	fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+		let s: String = Deserialize::deserialize(d)?;
+		// a leading `!` marks a denial; the rest is the host pattern
+		let (negated, s) = match s.strip_prefix('!') {
+			Some(rest) => (true, rest.to_string()),
+			None => (false, s),
+		};
+		// only treat the string as a glob when it carries pattern metacharacters
+		let matcher = if s.contains(['*', '?', '[']) {
+			HostMatcher::Pattern(s)
+		} else {
+			HostMatcher::Exact(s)
+		};
+		Ok(HostDescription { matcher, negated })
+	}
+}
+
+/// http status code emitted for a scheme redirection
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub enum RedirectStatus {
+	#[serde(rename = "301")]
+	MovedPermanently,
+	#[serde(rename = "302")]
+	Found,
+	#[serde(rename = "307")]
+	TemporaryRedirect,
+	#[serde(rename = "308")]
+	PermanentRedirect,
+}
+
+impl Default for RedirectStatus {
+	fn default() -> Self {
+		// default to a permanent upgrade for idempotent requests
+		RedirectStatus::PermanentRedirect
+	}
+}
+
+impl From<RedirectStatus> for StatusCode {
+	fn from(status: RedirectStatus) -> Self {
+		match status {
+			RedirectStatus::MovedPermanently => StatusCode::MOVED_PERMANENTLY,
+			RedirectStatus::Found => StatusCode::FOUND,
+			RedirectStatus::TemporaryRedirect => StatusCode::TEMPORARY_REDIRECT,
+			RedirectStatus::PermanentRedirect => StatusCode::PERMANENT_REDIRECT,
+		}
+	}
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -104,3 +311,164 @@ impl TryIntoHeaderPair for StrictTransportSecurity {
 		Ok((STRICT_TRANSPORT_SECURITY, value))
 	}
 }
+
+#[derive(Deserialize, Clone, Default)]
+/// response hardening headers configuration
+pub struct SecurityHeaders {
+	/// Strict-Transport-Security (disabled)
+	#[serde(default)]
+	pub hsts: Option<StrictTransportSecurity>,
+	/// emit `X-Content-Type-Options: nosniff` (false)
+	#[serde(default)]
+	pub content_type_options: bool,
+	/// value of the `X-Frame-Options` header (disabled)
+	#[serde(default, deserialize_with = "header_value_deser")]
+	pub frame_options: Option<HeaderValue>,
+	/// value of the `Referrer-Policy` header (disabled)
+	#[serde(default, deserialize_with = "header_value_deser")]
+	pub referrer_policy: Option<HeaderValue>,
+	/// value of the `Content-Security-Policy` header (disabled)
+	#[serde(default, deserialize_with = "header_value_deser")]
+	pub content_security_policy: Option<HeaderValue>,
+	/// value of the `Permissions-Policy` header (disabled)
+	#[serde(default, deserialize_with = "header_value_deser")]
+	pub permissions_policy: Option<HeaderValue>,
+}
+
+// Deserialize an optional header value, rejecting bytes `HeaderValue` forbids
+// at config-load time rather than panicking on every response.
+fn header_value_deser<'de, D: Deserializer<'de>>(d: D) -> Result<Option<HeaderValue>, D::Error> {
+	let s: Option<String> = Deserialize::deserialize(d)?;
+	match s {
+		Some(s) => HeaderValue::from_str(&s)
+			.map(Some)
+			.map_err(serde::de::Error::custom),
+		None => Ok(None),
+	}
+}
+
+impl SecurityHeaders {
+	/// Apply every configured header to a response.
+	pub fn insert_into<B>(&self, res: &mut HttpResponse<B>) {
+		self.insert_filtered(res, true);
+	}
+
+	/// Apply the configured headers, optionally skipping HSTS (which must only
+	/// be emitted for hosts that are actually being upgraded to https).
+	pub fn insert_filtered<B>(&self, res: &mut HttpResponse<B>, include_hsts: bool) {
+		if include_hsts {
+			if let Some(hsts) = &self.hsts {
+				hsts.insert_into(res);
+			}
+		}
+		let headers = res.headers_mut();
+		if self.content_type_options {
+			headers.insert(X_CONTENT_TYPE_OPTIONS, HeaderValue::from_static("nosniff"));
+		}
+		if let Some(value) = &self.frame_options {
+			headers.insert(X_FRAME_OPTIONS, value.clone());
+		}
+		if let Some(value) = &self.referrer_policy {
+			headers.insert(REFERRER_POLICY, value.clone());
+		}
+		if let Some(value) = &self.content_security_policy {
+			headers.insert(CONTENT_SECURITY_POLICY, value.clone());
+		}
+		if let Some(value) = &self.permissions_policy {
+			headers.insert(HeaderName::from_static("permissions-policy"), value.clone());
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{header_value_deser, HostDescription, RedirectStatus};
+	use actix_web::http::StatusCode;
+	use serde::{de::value::StrDeserializer, Deserialize};
+
+	type DeErr = serde::de::value::Error;
+
+	fn de<T: for<'de> Deserialize<'de>>(s: &str) -> Result<T, DeErr> {
+		T::deserialize(StrDeserializer::new(s))
+	}
+
+	// `StrDeserializer` hands a bare str to `Option`, which rejects it; this
+	// wrapper presents the string as `Some(str)`, as a config format does.
+	struct SomeStr<'a>(&'a str);
+
+	impl<'de, 'a> serde::Deserializer<'de> for SomeStr<'a> {
+		type Error = DeErr;
+
+		fn deserialize_any<V: serde::de::Visitor<'de>>(self, v: V) -> Result<V::Value, DeErr> {
+			v.visit_str(self.0)
+		}
+
+		fn deserialize_option<V: serde::de::Visitor<'de>>(self, v: V) -> Result<V::Value, DeErr> {
+			v.visit_some(StrDeserializer::<DeErr>::new(self.0))
+		}
+
+		serde::forward_to_deserialize_any! {
+			bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+			bytes byte_buf unit unit_struct newtype_struct seq tuple
+			tuple_struct map struct enum identifier ignored_any
+		}
+	}
+
+	#[test]
+	fn redirect_status_deserializes_string_tags() {
+		let cases = [
+			("301", StatusCode::MOVED_PERMANENTLY),
+			("302", StatusCode::FOUND),
+			("307", StatusCode::TEMPORARY_REDIRECT),
+			("308", StatusCode::PERMANENT_REDIRECT),
+		];
+		for (tag, code) in cases {
+			let status: RedirectStatus = de(tag).unwrap();
+			assert_eq!(StatusCode::from(status), code);
+		}
+		assert!(de::<RedirectStatus>("418").is_err());
+	}
+
+	#[test]
+	fn redirect_status_defaults_to_permanent() {
+		assert_eq!(
+			StatusCode::from(RedirectStatus::default()),
+			StatusCode::PERMANENT_REDIRECT
+		);
+	}
+
+	#[test]
+	fn host_description_exact_and_glob() {
+		let exact: HostDescription = de("api.example.com").unwrap();
+		assert!(exact.matches("api.example.com"));
+		assert!(!exact.matches("www.example.com"));
+		assert!(!exact.is_denial());
+
+		let glob: HostDescription = de("*.example.com").unwrap();
+		assert!(glob.matches("www.example.com"));
+		assert!(!glob.matches("example.org"));
+
+		let question: HostDescription = de("h?st.local").unwrap();
+		assert!(question.matches("host.local"));
+		assert!(!question.matches("haast.local"));
+
+		let class: HostDescription = de("node[0-9].local").unwrap();
+		assert!(class.matches("node3.local"));
+		assert!(!class.matches("nodex.local"));
+	}
+
+	#[test]
+	fn host_description_denial_prefix() {
+		let deny: HostDescription = de("!*.local").unwrap();
+		assert!(deny.is_denial());
+		assert!(deny.matches("internal.local"));
+	}
+
+	#[test]
+	fn header_value_deser_rejects_invalid_bytes() {
+		let ok = header_value_deser(SomeStr("DENY")).unwrap();
+		assert_eq!(ok.unwrap().to_str().unwrap(), "DENY");
+		// a stray newline is not a legal header value and must be refused
+		assert!(header_value_deser(SomeStr("bad\nvalue")).is_err());
+	}
+}
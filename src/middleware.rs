@@ -2,12 +2,16 @@ use actix_utils::future::{ready, Ready};
 use actix_web::{
 	body::EitherBody,
 	dev::{forward_ready, Service, ServiceRequest, ServiceResponse, Transform},
+	http::header,
 	web, Error, HttpResponse, Responder,
 };
 use futures_core::future::LocalBoxFuture;
 use std::{net::SocketAddr, rc::Rc};
 
-use crate::data::{Protocols, StrictTransportSecurity};
+use crate::data::{
+	default_exclude_paths, HostDescription, Protocols, RedirectStatus, SecurityHeaders,
+	StrictTransportSecurity,
+};
 
 // There are two steps in middleware processing.
 // 1. Middleware initialization, middleware factory gets called with
@@ -17,8 +21,12 @@ use crate::data::{Protocols, StrictTransportSecurity};
 #[derive(Clone, Default)]
 pub struct SchemeRedirect {
 	protocols: Protocols,
-	hsts: Option<StrictTransportSecurity>,
+	headers: Option<SecurityHeaders>,
 	port: Option<u16>,
+	status: RedirectStatus,
+	exclude_paths: Vec<String>,
+	hosts: Vec<HostDescription>,
+	redirect_upgrades: bool,
 }
 
 impl SchemeRedirect {
@@ -29,11 +37,48 @@ impl SchemeRedirect {
 	) -> Self {
 		Self {
 			protocols,
-			hsts,
+			headers: hsts.map(|hsts| SecurityHeaders {
+				hsts: Some(hsts),
+				..Default::default()
+			}),
 			port,
+			status: RedirectStatus::default(),
+			exclude_paths: default_exclude_paths(),
+			hosts: Vec::new(),
+			redirect_upgrades: false,
 		}
 	}
 
+	pub fn with_status(mut self, status: RedirectStatus) -> Self {
+		self.status = status;
+		self
+	}
+
+	/// Replace the list of path prefixes left on plain http.
+	pub fn with_exclude_paths(mut self, exclude_paths: Vec<String>) -> Self {
+		self.exclude_paths = exclude_paths;
+		self
+	}
+
+	/// Restrict redirection with the given host rules (allowances and `!`
+	/// denials); an empty list redirects every host.
+	pub fn with_hosts(mut self, hosts: Vec<HostDescription>) -> Self {
+		self.hosts = hosts;
+		self
+	}
+
+	/// Redirect WebSocket upgrade handshakes instead of forwarding them.
+	pub fn redirect_upgrades(mut self, redirect_upgrades: bool) -> Self {
+		self.redirect_upgrades = redirect_upgrades;
+		self
+	}
+
+	/// Replace the response hardening headers (HSTS and friends).
+	pub fn with_headers(mut self, headers: SecurityHeaders) -> Self {
+		self.headers = Some(headers);
+		self
+	}
+
 	pub fn to_port(mut self, port: u16) -> Self {
 		self.port = Some(port);
 		self
@@ -58,8 +103,12 @@ where
 		ready(Ok(SchemeRedirectMiddleware {
 			service: Rc::new(service),
 			protocols: self.protocols.clone(),
-			hsts: self.hsts.clone(),
+			headers: self.headers.clone(),
 			port: self.port,
+			status: self.status,
+			exclude_paths: self.exclude_paths.clone(),
+			hosts: self.hosts.clone(),
+			redirect_upgrades: self.redirect_upgrades,
 		}))
 	}
 }
@@ -67,8 +116,12 @@ where
 pub struct SchemeRedirectMiddleware<S> {
 	service: Rc<S>,
 	protocols: Protocols,
-	hsts: Option<StrictTransportSecurity>,
+	headers: Option<SecurityHeaders>,
 	port: Option<u16>,
+	status: RedirectStatus,
+	exclude_paths: Vec<String>,
+	hosts: Vec<HostDescription>,
+	redirect_upgrades: bool,
 }
 
 impl<S, B> Service<ServiceRequest> for SchemeRedirectMiddleware<S>
@@ -86,9 +139,17 @@ where
 	fn call(&self, req: ServiceRequest) -> Self::Future {
 		let service = Rc::clone(&self.service);
 		let port = self.port;
-		let hsts = self.hsts.clone();
+		let headers = self.headers.clone();
+		let status = self.status;
+		let hosts = self.hosts.clone();
+		// leave explicitly excluded prefixes (e.g. ACME challenges) on plain http
+		let excluded = is_excluded(&self.exclude_paths, req.path());
+		// a WebSocket handshake must not be answered with a 3xx redirect
+		let is_upgrade = !self.redirect_upgrades && is_websocket_upgrade(req.headers());
 		// check if we need to redirect
-		let to_redirect = !matches!(self.protocols, Protocols::None)
+		let to_redirect = !excluded
+			&& !is_upgrade
+			&& !matches!(self.protocols, Protocols::None)
 			&& req
 				.peer_addr()
 				.and_then(|a| match a {
@@ -121,10 +182,32 @@ where
 		Box::pin(async move {
 			let (req, pl) = req.into_parts();
 			let conn_info = req.connection_info();
-			if to_redirect && conn_info.scheme() != "https" {
+			// apply host rules: a matching denial (`!host`) wins; otherwise, when
+			// any allowance is configured the host must match one of them
+			let host_match = {
 				let host = conn_info.host();
 				let (hostname, _port) = host.split_once(':').unwrap_or((host, ""));
-				let path = req.uri().path();
+				let denied = hosts
+					.iter()
+					.filter(|h| h.is_denial())
+					.any(|h| h.matches(hostname));
+				let has_allow = hosts.iter().any(|h| !h.is_denial());
+				let allowed = !has_allow
+					|| hosts
+						.iter()
+						.filter(|h| !h.is_denial())
+						.any(|h| h.matches(hostname));
+				!denied && allowed
+			};
+			if host_match && to_redirect && conn_info.scheme() != "https" {
+				let host = conn_info.host();
+				let (hostname, _port) = host.split_once(':').unwrap_or((host, ""));
+				// keep the query string so `?foo=bar` survives the hop
+				let path = req
+					.uri()
+					.path_and_query()
+					.map(|pq| pq.as_str())
+					.unwrap_or("/");
 				let uri = match port {
 					Some(port) => format!("https://{hostname}:{port}{path}"),
 					None => format!("https://{hostname}{path}"),
@@ -132,11 +215,11 @@ where
 				// all connection info is acquired
 				drop(conn_info);
 
-				// create redirection response
-				let redirect = web::Redirect::to(uri);
+				// create redirection response with the configured status code
+				let redirect = web::Redirect::to(uri).using_status_code(status.into());
 
 				let mut res = redirect.respond_to(&req).map_into_right_body();
-				apply_hsts(&mut res, hsts);
+				apply_headers(&mut res, headers, true);
 
 				return Ok(ServiceResponse::new(req, res));
 			}
@@ -145,16 +228,77 @@ where
 
 			let req = ServiceRequest::from_parts(req, pl);
 			service.call(req).await.map(|mut res| {
-				apply_hsts(res.response_mut(), hsts);
+				// hardening headers apply to every host; HSTS only to allowlisted ones
+				apply_headers(res.response_mut(), headers, host_match);
 				res.map_into_left_body()
 			})
 		})
 	}
 }
 
-/// Apply HSTS config to an `HttpResponse`.
-fn apply_hsts<B>(res: &mut HttpResponse<B>, hsts: Option<StrictTransportSecurity>) {
-	if let Some(hsts) = hsts {
-		hsts.insert_into(res);
+/// Apply the configured security headers to an `HttpResponse`. HSTS is only
+/// emitted when `include_hsts` is set (i.e. the host is being upgraded).
+fn apply_headers<B>(res: &mut HttpResponse<B>, headers: Option<SecurityHeaders>, include_hsts: bool) {
+	if let Some(headers) = headers {
+		headers.insert_filtered(res, include_hsts);
+	}
+}
+
+/// Whether `path` falls under one of the configured plain-http prefixes.
+fn is_excluded(exclude_paths: &[String], path: &str) -> bool {
+	exclude_paths
+		.iter()
+		.any(|prefix| path.starts_with(prefix.as_str()))
+}
+
+/// Detect a WebSocket handshake from the `Connection` and `Upgrade` headers.
+fn is_websocket_upgrade(headers: &actix_web::http::header::HeaderMap) -> bool {
+	let connection_upgrade = headers
+		.get(header::CONNECTION)
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.split(',').any(|t| t.trim().eq_ignore_ascii_case("upgrade")))
+		.unwrap_or(false);
+	let upgrade_websocket = headers
+		.get(header::UPGRADE)
+		.and_then(|v| v.to_str().ok())
+		.map(|v| v.eq_ignore_ascii_case("websocket"))
+		.unwrap_or(false);
+	connection_upgrade && upgrade_websocket
+}
+
+#[cfg(test)]
+mod tests {
+	use super::{is_excluded, is_websocket_upgrade};
+	use actix_web::http::header::{HeaderMap, HeaderName, HeaderValue, CONNECTION, UPGRADE};
+
+	#[test]
+	fn excludes_configured_prefixes() {
+		let paths = vec!["/.well-known/acme-challenge/".to_string()];
+		assert!(is_excluded(&paths, "/.well-known/acme-challenge/token"));
+		assert!(!is_excluded(&paths, "/index.html"));
+		assert!(!is_excluded(&[], "/.well-known/acme-challenge/token"));
+	}
+
+	#[test]
+	fn detects_websocket_upgrade() {
+		let mut headers = HeaderMap::new();
+		headers.insert(CONNECTION, HeaderValue::from_static("keep-alive, Upgrade"));
+		headers.insert(UPGRADE, HeaderValue::from_static("websocket"));
+		assert!(is_websocket_upgrade(&headers));
+	}
+
+	#[test]
+	fn ignores_non_upgrade_requests() {
+		let mut headers = HeaderMap::new();
+		headers.insert(CONNECTION, HeaderValue::from_static("keep-alive"));
+		assert!(!is_websocket_upgrade(&headers));
+
+		let mut upgrade_other = HeaderMap::new();
+		upgrade_other.insert(CONNECTION, HeaderValue::from_static("Upgrade"));
+		upgrade_other.insert(
+			HeaderName::from_static("upgrade"),
+			HeaderValue::from_static("h2c"),
+		);
+		assert!(!is_websocket_upgrade(&upgrade_other));
 	}
 }